@@ -0,0 +1,446 @@
+// Copyright 2022-2024 Tauri Programme within The Commons Conservancy
+// SPDX-License-Identifier: Apache-2.0
+// SPDX-License-Identifier: MIT
+
+//! A fallback backend for compositors that implement neither X11 grabbing nor the XDG
+//! `global_shortcuts` portal (most wlroots-based compositors, gamescope, bare TTY sessions).
+//!
+//! It is selected when `XDG_SESSION_TYPE` is unset or is neither `x11` nor `wayland`, and in a
+//! `wayland` session only if the wayland backend's constructor returns an error. That
+//! constructor does not check for the portal, so a wayland compositor without it still gets
+//! the wayland backend rather than this one.
+//!
+//! This reads raw key events from `/dev/input/event*` via `evdev`, so the running user must be
+//! a member of the `input` group (or have equivalent udev rules granting read/write access to
+//! those device nodes) for it to see any keys at all.
+//!
+//! Devices are only `grab()`'d once a `uinput` passthrough device can be created for them, and
+//! every event read is re-emitted through it: `EVIOCGRAB` makes this process the device's
+//! exclusive receiver, so without passthrough the compositor (and everything else) would stop
+//! seeing keyboard input at all the moment this backend came online.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use crossbeam_channel::{unbounded, Receiver, Sender};
+use evdev::uinput::VirtualDevice;
+use evdev::{Device, InputEventKind, Key};
+use keyboard_types::{Code, Modifiers};
+
+use crate::hotkey::HotKey;
+use crate::{GlobalHotKeyEvent, HotKeyState};
+
+// evdev fires autorepeat key-down events for a held key; ignore re-fires of a hotkey that
+// already fired within this window so autorepeat doesn't spam Pressed events.
+const REPEAT_GUARD: Duration = Duration::from_millis(400);
+
+enum ThreadMessage {
+    RegisterHotKey(HotKey, Sender<crate::Result<()>>),
+    RegisterHotKeys(Vec<HotKey>, Sender<crate::Result<()>>),
+    UnRegisterHotKey(HotKey, Sender<crate::Result<()>>),
+    UnRegisterHotKeys(Vec<HotKey>, Sender<crate::Result<()>>),
+    DropThread,
+}
+
+// Shared between the command thread and every per-device reader thread.
+#[derive(Default)]
+struct Registry {
+    hotkeys: HashMap<(Modifiers, Code), u32>,
+    last_fired: HashMap<u32, SystemTime>,
+}
+
+pub struct GlobalHotKeyManager {
+    thread_tx: Sender<ThreadMessage>,
+}
+
+impl GlobalHotKeyManager {
+    pub fn new() -> crate::Result<Self> {
+        let (thread_tx, thread_rx) = unbounded();
+        std::thread::spawn(|| events_processor(thread_rx));
+        Ok(Self { thread_tx })
+    }
+
+    pub fn register(&self, hotkey: HotKey) -> crate::Result<()> {
+        let (tx, rx) = crossbeam_channel::bounded(1);
+        let _ = self
+            .thread_tx
+            .send(ThreadMessage::RegisterHotKey(hotkey, tx));
+
+        if let Ok(result) = rx.recv() {
+            result?;
+        }
+
+        Ok(())
+    }
+
+    pub fn unregister(&self, hotkey: HotKey) -> crate::Result<()> {
+        let (tx, rx) = crossbeam_channel::bounded(1);
+        let _ = self
+            .thread_tx
+            .send(ThreadMessage::UnRegisterHotKey(hotkey, tx));
+
+        if let Ok(result) = rx.recv() {
+            result?;
+        }
+
+        Ok(())
+    }
+
+    pub fn register_all(&self, hotkeys: &[HotKey]) -> crate::Result<()> {
+        let (tx, rx) = crossbeam_channel::bounded(1);
+        let _ = self
+            .thread_tx
+            .send(ThreadMessage::RegisterHotKeys(hotkeys.to_vec(), tx));
+
+        if let Ok(result) = rx.recv() {
+            result?;
+        }
+
+        Ok(())
+    }
+
+    pub fn unregister_all(&self, hotkeys: &[HotKey]) -> crate::Result<()> {
+        let (tx, rx) = crossbeam_channel::bounded(1);
+        let _ = self
+            .thread_tx
+            .send(ThreadMessage::UnRegisterHotKeys(hotkeys.to_vec(), tx));
+
+        if let Ok(result) = rx.recv() {
+            result?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for GlobalHotKeyManager {
+    fn drop(&mut self) {
+        let _ = self.thread_tx.send(ThreadMessage::DropThread);
+    }
+}
+
+fn events_processor(thread_rx: Receiver<ThreadMessage>) {
+    let registry = Arc::new(Mutex::new(Registry::default()));
+
+    for device in open_keyboards() {
+        let registry = registry.clone();
+        std::thread::spawn(move || read_device(device, registry));
+    }
+
+    loop {
+        match thread_rx.recv() {
+            Ok(ThreadMessage::RegisterHotKey(hotkey, tx)) => {
+                let _ = tx.send(register_hotkey(&registry, hotkey));
+            }
+            Ok(ThreadMessage::RegisterHotKeys(keys, tx)) => {
+                let mut result = Ok(());
+                for hotkey in keys {
+                    if let Err(e) = register_hotkey(&registry, hotkey) {
+                        result = Err(e);
+                    }
+                }
+                let _ = tx.send(result);
+            }
+            Ok(ThreadMessage::UnRegisterHotKey(hotkey, tx)) => {
+                let _ = tx.send(unregister_hotkey(&registry, hotkey));
+            }
+            Ok(ThreadMessage::UnRegisterHotKeys(keys, tx)) => {
+                let mut result = Ok(());
+                for hotkey in keys {
+                    if let Err(e) = unregister_hotkey(&registry, hotkey) {
+                        result = Err(e);
+                    }
+                }
+                let _ = tx.send(result);
+            }
+            Ok(ThreadMessage::DropThread) | Err(_) => return,
+        }
+    }
+}
+
+fn register_hotkey(registry: &Arc<Mutex<Registry>>, hotkey: HotKey) -> crate::Result<()> {
+    registry
+        .lock()
+        .unwrap()
+        .hotkeys
+        .insert((hotkey.mods, hotkey.key), hotkey.id());
+    Ok(())
+}
+
+fn unregister_hotkey(registry: &Arc<Mutex<Registry>>, hotkey: HotKey) -> crate::Result<()> {
+    registry
+        .lock()
+        .unwrap()
+        .hotkeys
+        .remove(&(hotkey.mods, hotkey.key));
+    Ok(())
+}
+
+fn open_keyboards() -> Vec<Device> {
+    let mut devices = Vec::new();
+
+    let Ok(read_dir) = std::fs::read_dir("/dev/input") else {
+        #[cfg(debug_assertions)]
+        eprintln!("Failed to read /dev/input, the evdev backend needs the current user to be a member of the `input` group.");
+        return devices;
+    };
+
+    for entry in read_dir.flatten() {
+        let path = entry.path();
+        let is_event_node = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with("event"));
+        if !is_event_node {
+            continue;
+        }
+
+        let Ok(device) = Device::open(&path) else {
+            continue;
+        };
+
+        // Only keep devices that look like keyboards, i.e. they can produce ordinary letters.
+        let is_keyboard = device
+            .supported_keys()
+            .is_some_and(|keys| keys.contains(Key::KEY_A) && keys.contains(Key::KEY_Z));
+        if !is_keyboard {
+            continue;
+        }
+
+        devices.push(device);
+    }
+
+    devices
+}
+
+fn read_device(mut device: Device, registry: Arc<Mutex<Registry>>) {
+    let mut held_modifiers: HashSet<Key> = HashSet::new();
+    let mut last_key: Option<Key> = None;
+    // Physical key -> hotkey id that was active when it went down, so its key-up fires the
+    // matching Released event regardless of what's happened to the modifier keys in the
+    // meantime (letting go of Ctrl before the letter is the common case that breaks a naive
+    // "recompute the combo from the live modifier set" release check).
+    let mut down: HashMap<Key, u32> = HashMap::new();
+
+    let mut passthrough = build_passthrough(&device);
+    if passthrough.is_some() {
+        let _ = device.grab();
+    }
+
+    loop {
+        let events = match device.fetch_events() {
+            Ok(events) => events,
+            Err(_) => return,
+        };
+
+        for event in events {
+            if let Some(passthrough) = passthrough.as_mut() {
+                let _ = passthrough.emit(&[event]);
+            }
+
+            let InputEventKind::Key(key) = event.kind() else {
+                continue;
+            };
+
+            // evdev key values: 0 = released, 1 = pressed, 2 = autorepeat.
+            match event.value() {
+                0 => {
+                    if evdev_key_to_modifier(key).is_some() {
+                        held_modifiers.remove(&key);
+                    } else if let Some(id) = down.remove(&key) {
+                        GlobalHotKeyEvent::send(GlobalHotKeyEvent {
+                            id,
+                            state: HotKeyState::Released,
+                        });
+                    }
+
+                    if last_key == Some(key) {
+                        last_key = None;
+                    }
+                }
+                1 | 2 => {
+                    if let Some(modifier) = evdev_key_to_modifier(key) {
+                        held_modifiers.insert(key);
+                        let _ = modifier;
+                        continue;
+                    }
+
+                    let Some(code) = evdev_key_to_code(key) else {
+                        continue;
+                    };
+
+                    let is_repeat = event.value() == 2;
+                    let is_same_key = last_key == Some(key);
+                    last_key = Some(key);
+
+                    if is_repeat && is_same_key {
+                        continue;
+                    }
+
+                    let modifiers = held_modifiers
+                        .iter()
+                        .filter_map(|key| evdev_key_to_modifier(*key))
+                        .fold(Modifiers::empty(), |acc, m| acc | m);
+
+                    if let Some(id) = fire_if_registered(&registry, modifiers, code) {
+                        down.insert(key, id);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+// Builds a `uinput` virtual device that mirrors `device`'s key events back to the system, so
+// grabbing `device` exclusively doesn't stop normal typing from reaching the compositor and
+// every other app. Returns `None` (and the caller skips the `grab()`) if it can't be created,
+// e.g. missing access to `/dev/uinput`.
+fn build_passthrough(device: &Device) -> Option<VirtualDevice> {
+    let keys = device.supported_keys()?.to_owned();
+    evdev::uinput::VirtualDeviceBuilder::new()
+        .ok()?
+        .name("global-hotkey evdev passthrough")
+        .with_keys(&keys)
+        .ok()?
+        .build()
+        .ok()
+}
+
+fn fire_if_registered(registry: &Arc<Mutex<Registry>>, modifiers: Modifiers, code: Code) -> Option<u32> {
+    let mut registry = registry.lock().unwrap();
+    let &id = registry.hotkeys.get(&(modifiers, code))?;
+
+    let now = SystemTime::now();
+    if let Some(last) = registry.last_fired.get(&id) {
+        if now.duration_since(*last).unwrap_or(Duration::MAX) < REPEAT_GUARD {
+            return None;
+        }
+    }
+    registry.last_fired.insert(id, now);
+
+    GlobalHotKeyEvent::send(GlobalHotKeyEvent {
+        id,
+        state: HotKeyState::Pressed,
+    });
+
+    Some(id)
+}
+
+// The inverse of `keycode_to_freedesktop_spec` in the wayland backend, mapping evdev scancodes
+// to `keyboard-types` codes instead of the freedesktop shortcut spec's key names.
+fn evdev_key_to_code(key: Key) -> Option<Code> {
+    Some(match key {
+        Key::KEY_A => Code::KeyA,
+        Key::KEY_B => Code::KeyB,
+        Key::KEY_C => Code::KeyC,
+        Key::KEY_D => Code::KeyD,
+        Key::KEY_E => Code::KeyE,
+        Key::KEY_F => Code::KeyF,
+        Key::KEY_G => Code::KeyG,
+        Key::KEY_H => Code::KeyH,
+        Key::KEY_I => Code::KeyI,
+        Key::KEY_J => Code::KeyJ,
+        Key::KEY_K => Code::KeyK,
+        Key::KEY_L => Code::KeyL,
+        Key::KEY_M => Code::KeyM,
+        Key::KEY_N => Code::KeyN,
+        Key::KEY_O => Code::KeyO,
+        Key::KEY_P => Code::KeyP,
+        Key::KEY_Q => Code::KeyQ,
+        Key::KEY_R => Code::KeyR,
+        Key::KEY_S => Code::KeyS,
+        Key::KEY_T => Code::KeyT,
+        Key::KEY_U => Code::KeyU,
+        Key::KEY_V => Code::KeyV,
+        Key::KEY_W => Code::KeyW,
+        Key::KEY_X => Code::KeyX,
+        Key::KEY_Y => Code::KeyY,
+        Key::KEY_Z => Code::KeyZ,
+        Key::KEY_0 => Code::Digit0,
+        Key::KEY_1 => Code::Digit1,
+        Key::KEY_2 => Code::Digit2,
+        Key::KEY_3 => Code::Digit3,
+        Key::KEY_4 => Code::Digit4,
+        Key::KEY_5 => Code::Digit5,
+        Key::KEY_6 => Code::Digit6,
+        Key::KEY_7 => Code::Digit7,
+        Key::KEY_8 => Code::Digit8,
+        Key::KEY_9 => Code::Digit9,
+        Key::KEY_BACKSLASH => Code::Backslash,
+        Key::KEY_LEFTBRACE => Code::BracketLeft,
+        Key::KEY_RIGHTBRACE => Code::BracketRight,
+        Key::KEY_GRAVE => Code::Backquote,
+        Key::KEY_COMMA => Code::Comma,
+        Key::KEY_EQUAL => Code::Equal,
+        Key::KEY_MINUS => Code::Minus,
+        Key::KEY_DOT => Code::Period,
+        Key::KEY_APOSTROPHE => Code::Quote,
+        Key::KEY_SEMICOLON => Code::Semicolon,
+        Key::KEY_SLASH => Code::Slash,
+        Key::KEY_BACKSPACE => Code::Backspace,
+        Key::KEY_CAPSLOCK => Code::CapsLock,
+        Key::KEY_ENTER => Code::Enter,
+        Key::KEY_SPACE => Code::Space,
+        Key::KEY_TAB => Code::Tab,
+        Key::KEY_DELETE => Code::Delete,
+        Key::KEY_END => Code::End,
+        Key::KEY_HOME => Code::Home,
+        Key::KEY_INSERT => Code::Insert,
+        Key::KEY_PAGEDOWN => Code::PageDown,
+        Key::KEY_PAGEUP => Code::PageUp,
+        Key::KEY_DOWN => Code::ArrowDown,
+        Key::KEY_LEFT => Code::ArrowLeft,
+        Key::KEY_RIGHT => Code::ArrowRight,
+        Key::KEY_UP => Code::ArrowUp,
+        Key::KEY_KP0 => Code::Numpad0,
+        Key::KEY_KP1 => Code::Numpad1,
+        Key::KEY_KP2 => Code::Numpad2,
+        Key::KEY_KP3 => Code::Numpad3,
+        Key::KEY_KP4 => Code::Numpad4,
+        Key::KEY_KP5 => Code::Numpad5,
+        Key::KEY_KP6 => Code::Numpad6,
+        Key::KEY_KP7 => Code::Numpad7,
+        Key::KEY_KP8 => Code::Numpad8,
+        Key::KEY_KP9 => Code::Numpad9,
+        Key::KEY_KPPLUS => Code::NumpadAdd,
+        Key::KEY_KPDOT => Code::NumpadDecimal,
+        Key::KEY_KPSLASH => Code::NumpadDivide,
+        Key::KEY_KPASTERISK => Code::NumpadMultiply,
+        Key::KEY_KPMINUS => Code::NumpadSubtract,
+        Key::KEY_ESC => Code::Escape,
+        Key::KEY_SYSRQ => Code::PrintScreen,
+        Key::KEY_SCROLLLOCK => Code::ScrollLock,
+        Key::KEY_F1 => Code::F1,
+        Key::KEY_F2 => Code::F2,
+        Key::KEY_F3 => Code::F3,
+        Key::KEY_F4 => Code::F4,
+        Key::KEY_F5 => Code::F5,
+        Key::KEY_F6 => Code::F6,
+        Key::KEY_F7 => Code::F7,
+        Key::KEY_F8 => Code::F8,
+        Key::KEY_F9 => Code::F9,
+        Key::KEY_F10 => Code::F10,
+        Key::KEY_F11 => Code::F11,
+        Key::KEY_F12 => Code::F12,
+        Key::KEY_VOLUMEDOWN => Code::AudioVolumeDown,
+        Key::KEY_MUTE => Code::AudioVolumeMute,
+        Key::KEY_VOLUMEUP => Code::AudioVolumeUp,
+        Key::KEY_PLAYPAUSE => Code::MediaPlay,
+        Key::KEY_STOPCD => Code::MediaStop,
+        Key::KEY_NEXTSONG => Code::MediaTrackNext,
+        Key::KEY_PREVIOUSSONG => Code::MediaTrackPrevious,
+        _ => return None,
+    })
+}
+
+fn evdev_key_to_modifier(key: Key) -> Option<Modifiers> {
+    Some(match key {
+        Key::KEY_LEFTSHIFT | Key::KEY_RIGHTSHIFT => Modifiers::SHIFT,
+        Key::KEY_LEFTCTRL | Key::KEY_RIGHTCTRL => Modifiers::CONTROL,
+        Key::KEY_LEFTALT | Key::KEY_RIGHTALT => Modifiers::ALT,
+        Key::KEY_LEFTMETA | Key::KEY_RIGHTMETA => Modifiers::SUPER,
+        _ => return None,
+    })
+}
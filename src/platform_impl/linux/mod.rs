@@ -10,9 +10,13 @@ mod x11;
 #[path = "wayland/mod.rs"]
 mod wayland;
 
+#[path = "evdev/mod.rs"]
+mod evdev;
+
 pub(crate) enum GlobalHotKeyManager {
     X11(x11::GlobalHotKeyManager),
     Wayland(wayland::GlobalHotKeyManager),
+    Evdev(evdev::GlobalHotKeyManager),
 }
 
 impl GlobalHotKeyManager {
@@ -23,23 +27,24 @@ impl GlobalHotKeyManager {
                 match env_str {
                     "x11" => x11::GlobalHotKeyManager::new().map(GlobalHotKeyManager::X11),
 
-                    "wayland" => wayland::GlobalHotKeyManager::new().map(GlobalHotKeyManager::Wayland),
+                    "wayland" => wayland::GlobalHotKeyManager::new()
+                        .map(GlobalHotKeyManager::Wayland)
+                        .or_else(|_| evdev::GlobalHotKeyManager::new().map(GlobalHotKeyManager::Evdev)),
                     _ => {
-                        let error = std::io::Error::new(std::io::ErrorKind::NotFound, format!("Unknown XDG_SESSION_TYPE: {}, expected x11 or wayland.", env_str));
-                        Err(crate::Error::OsError(error))
+                        #[cfg(debug_assertions)]
+                        eprintln!("Unknown XDG_SESSION_TYPE: {}, falling back to the evdev backend.", env_str);
+                        evdev::GlobalHotKeyManager::new().map(GlobalHotKeyManager::Evdev)
                     },
                 }
             },
-            Err(e) => {
-                let error = std::io::Error::new(std::io::ErrorKind::Other, e);
-                Err(crate::Error::OsError(error))
-            },
+            Err(_) => evdev::GlobalHotKeyManager::new().map(GlobalHotKeyManager::Evdev),
         }
     }
     pub(crate) fn register(&self, hotkey: HotKey) -> crate::Result<()> {
         match self {
             GlobalHotKeyManager::Wayland(wayland) => {wayland.register(hotkey)},
             GlobalHotKeyManager::X11(x11) => {x11.register(hotkey)},
+            GlobalHotKeyManager::Evdev(evdev) => {evdev.register(hotkey)},
         }
     }
 
@@ -47,12 +52,14 @@ impl GlobalHotKeyManager {
         match self {
             GlobalHotKeyManager::Wayland(wayland) => {wayland.unregister(hotkey)},
             GlobalHotKeyManager::X11(x11) => {x11.unregister(hotkey)},
+            GlobalHotKeyManager::Evdev(evdev) => {evdev.unregister(hotkey)},
         }
     }
     pub(crate) fn register_all(&self, hotkeys: &[HotKey]) -> crate::Result<()> {
         match self {
             GlobalHotKeyManager::Wayland(wayland) => {wayland.register_all(hotkeys)},
             GlobalHotKeyManager::X11(x11) => {x11.register_all(hotkeys)},
+            GlobalHotKeyManager::Evdev(evdev) => {evdev.register_all(hotkeys)},
         }
     }
 
@@ -60,6 +67,28 @@ impl GlobalHotKeyManager {
         match self {
             GlobalHotKeyManager::Wayland(wayland) => {wayland.unregister_all(hotkeys)},
             GlobalHotKeyManager::X11(x11) => {x11.unregister_all(hotkeys)},
+            GlobalHotKeyManager::Evdev(evdev) => {evdev.unregister_all(hotkeys)},
+        }
+    }
+
+    /// Same as [`Self::register`], but attaches a human-readable label. Only the wayland portal
+    /// backend surfaces this to the user (in the compositor's own shortcut settings UI); other
+    /// backends register the hotkey as usual and ignore the description.
+    pub(crate) fn register_with_description(&self, hotkey: HotKey, description: Option<String>) -> crate::Result<()> {
+        match self {
+            GlobalHotKeyManager::Wayland(wayland) => {wayland.register_with_description(hotkey, description)},
+            GlobalHotKeyManager::X11(x11) => {x11.register(hotkey)},
+            GlobalHotKeyManager::Evdev(evdev) => {evdev.register(hotkey)},
+        }
+    }
+
+    /// The trigger the compositor actually assigned to this hotkey, if known. Only meaningful
+    /// under the wayland portal backend, since it's the only one where the user can reassign a
+    /// shortcut to a different key combination than the one requested.
+    pub(crate) fn assigned_trigger(&self, hotkey: &HotKey) -> Option<String> {
+        match self {
+            GlobalHotKeyManager::Wayland(wayland) => wayland.assigned_trigger(hotkey),
+            GlobalHotKeyManager::X11(_) | GlobalHotKeyManager::Evdev(_) => None,
         }
     }
 
@@ -9,14 +9,15 @@ use ashpd::Error;
 use crossbeam_channel::{unbounded, Receiver, Sender};
 use keyboard_types::{Code, Modifiers};
 use crate::{hotkey::HotKey, GlobalHotKeyEvent};
-use tokio::runtime::Runtime;
-use crate::platform_impl::platform::wayland::runtime::Xdgs;
+use tokio_stream::StreamExt;
+use crate::platform_impl::platform::wayland::runtime::AsyncXdgs;
 
 enum ThreadMessage {
-    RegisterHotKey(HotKey, Sender<crate::Result<()>>),
+    RegisterHotKey(HotKey, Option<String>, Sender<crate::Result<()>>),
     RegisterHotKeys(Vec<HotKey>, Sender<crate::Result<()>>),
     UnRegisterHotKey(HotKey, Sender<crate::Result<()>>),
     UnRegisterHotKeys(Vec<HotKey>, Sender<crate::Result<()>>),
+    AssignedTrigger(HotKey, Sender<Option<String>>),
     DropThread,
 }
 
@@ -32,10 +33,22 @@ impl GlobalHotKeyManager {
     }
 
     pub fn register(&self, hotkey: HotKey) -> crate::Result<()> {
+        self.register_with_description(hotkey, None)
+    }
+
+    /// Same as [`Self::register`], but attaches a human-readable label that the compositor
+    /// shows for this shortcut in its own settings UI instead of the default generic text.
+    /// The label is not kept when `unregister`/`unregister_all` rebind the session: the
+    /// shortcuts that remain are bound again with the default text.
+    pub fn register_with_description(
+        &self,
+        hotkey: HotKey,
+        description: Option<String>,
+    ) -> crate::Result<()> {
         let (tx, rx) = crossbeam_channel::bounded(1);
         let _ = self
             .thread_tx
-            .send(ThreadMessage::RegisterHotKey(hotkey, tx));
+            .send(ThreadMessage::RegisterHotKey(hotkey, description, tx));
 
         if let Ok(result) = rx.recv() {
             result?;
@@ -44,6 +57,17 @@ impl GlobalHotKeyManager {
         Ok(())
     }
 
+    /// The trigger the compositor actually bound to this hotkey, if known. The portal lets the
+    /// user reassign a shortcut to a different key combination than the one requested, so this
+    /// is the authoritative binding rather than an echo of what was registered.
+    pub fn assigned_trigger(&self, hotkey: &HotKey) -> Option<String> {
+        let (tx, rx) = crossbeam_channel::bounded(1);
+        let _ = self
+            .thread_tx
+            .send(ThreadMessage::AssignedTrigger(*hotkey, tx));
+        rx.recv().ok().flatten()
+    }
+
     pub fn unregister(&self, hotkey: HotKey) -> crate::Result<()> {
         let (tx, rx) = crossbeam_channel::bounded(1);
         let _ = self
@@ -91,10 +115,11 @@ impl Drop for GlobalHotKeyManager {
 }
 
 #[inline]
-fn register_hotkey(
-    xdgs: &Xdgs,
+async fn register_hotkey(
+    xdg: &AsyncXdgs<'_>,
     hotkeys: &mut Vec<u32>,
     hotkey: HotKey,
+    description: Option<String>,
 ) -> crate::Result<()> {
     let (modifiers, key) = (
         modifiers_to_freedesktop_spec(hotkey.mods),
@@ -103,7 +128,9 @@ fn register_hotkey(
 
     if let Some(key) = key {
         let xdg_shortcut  = format!("{}+{}", modifiers, key);
-        xdgs.register(xdg_shortcut, hotkeys)
+        xdg.register(xdg_shortcut, description, hotkeys)
+            .await
+            .map_err(|e| crate::Error::FailedToRegister(e.to_string()))
     } else {
         Err(crate::Error::FailedToRegister(format!(
             "Unable to register accelerator (unknown scancode for this key: {}).",
@@ -113,8 +140,8 @@ fn register_hotkey(
 }
 
 #[inline]
-fn unregister_hotkey(
-    xdgs: &Xdgs,
+async fn unregister_hotkey(
+    xdg: &AsyncXdgs<'_>,
     hotkeys: &mut Vec<u32>,
     hotkey: HotKey,
 ) -> crate::Result<()> {
@@ -125,66 +152,133 @@ fn unregister_hotkey(
 
     if let Some(key) = key {
         let xdg_shortcut = format!("{}+{}", modifiers, key);
-        xdgs.unregister(xdg_shortcut, hotkey, hotkeys)
+        xdg.unregister(xdg_shortcut, hotkeys)
+            .await
+            .map_err(|_| crate::Error::FailedToUnRegister(hotkey))
     } else {
         Err(crate::Error::FailedToUnRegister(hotkey))
     }
 }
 
+// Unlike `unregister_hotkey`, this does a single session rebind for the whole batch instead of
+// one rebind per hotkey.
+#[inline]
+async fn unregister_hotkeys(
+    xdg: &AsyncXdgs<'_>,
+    hotkeys: &mut Vec<u32>,
+    keys: Vec<HotKey>,
+) -> crate::Result<()> {
+    let mut xdg_shortcuts = Vec::with_capacity(keys.len());
+    for hotkey in &keys {
+        let (modifiers, key) = (
+            modifiers_to_freedesktop_spec(hotkey.mods),
+            keycode_to_freedesktop_spec(hotkey.key),
+        );
+        match key {
+            Some(key) => xdg_shortcuts.push(format!("{}+{}", modifiers, key)),
+            None => return Err(crate::Error::FailedToUnRegister(*hotkey)),
+        }
+    }
+
+    xdg.unregister_all(&xdg_shortcuts, hotkeys)
+        .await
+        .map_err(|_| crate::Error::FailedToUnRegister(keys[0]))
+}
+
 fn events_processor(thread_rx: Receiver<ThreadMessage>) {
-    let mut hotkeys: Vec<u32> = Vec::new();
-    if let Ok(xdg) = Xdgs::new() {
-        loop {
-            if let Ok(msg) = thread_rx.try_recv() {
+    let Ok(rt) = tokio::runtime::Builder::new_current_thread()
+        .enable_io()
+        .enable_time()
+        .build()
+    else {
+        return;
+    };
+
+    rt.block_on(run(thread_rx));
+}
+
+// A single long-lived task that keeps the command channel, the activation stream and the
+// deactivation stream all hot at once and `select!`s over them, so two hotkeys firing close
+// together are both observed instead of only the first one polled.
+async fn run(thread_rx: Receiver<ThreadMessage>) {
+    let Ok(xdg) = AsyncXdgs::new().await else {
+        #[cfg(debug_assertions)]
+        eprintln!("Failed to open global shortcut portal, it might not be implemented on your desktop environment. The portal is required for global-hotkey crate under wayland try x11 instead.");
+        return;
+    };
+
+    let Ok(mut activated) = xdg.activated_stream().await else {
+        return;
+    };
+    let Ok(mut deactivated) = xdg.deactivated_stream().await else {
+        return;
+    };
+
+    // crossbeam_channel::Receiver::recv() blocks, so forward it into a tokio mpsc channel from
+    // a small dedicated thread to make it awaitable alongside the portal streams.
+    let (cmd_tx, mut cmd_rx) = tokio::sync::mpsc::unbounded_channel();
+    std::thread::spawn(move || {
+        while let Ok(msg) = thread_rx.recv() {
+            let is_drop = matches!(msg, ThreadMessage::DropThread);
+            if cmd_tx.send(msg).is_err() || is_drop {
+                break;
+            }
+        }
+    });
+
+    let mut hotkeys: Vec<u32> = xdg.known_hotkeys();
+
+    loop {
+        tokio::select! {
+            msg = cmd_rx.recv() => {
                 match msg {
-                    ThreadMessage::RegisterHotKey(hotkey, tx) => {
-                        let _ = tx.send(register_hotkey(
-                            &xdg,
-                            &mut hotkeys,
-                            hotkey,
-                        ));
+                    Some(ThreadMessage::RegisterHotKey(hotkey, description, tx)) => {
+                        let _ = tx.send(register_hotkey(&xdg, &mut hotkeys, hotkey, description).await);
                     }
-                    ThreadMessage::RegisterHotKeys(keys, tx) => {
+                    Some(ThreadMessage::RegisterHotKeys(keys, tx)) => {
+                        let mut result = Ok(());
                         for hotkey in keys {
-                            if let Err(e) =
-                                register_hotkey(&xdg, &mut hotkeys, hotkey)
-                            {
-                                let _ = tx.send(Err(e));
+                            if let Err(e) = register_hotkey(&xdg, &mut hotkeys, hotkey, None).await {
+                                result = Err(e);
                             }
                         }
-                        let _ = tx.send(Ok(()));
+                        let _ = tx.send(result);
                     }
-                    ThreadMessage::UnRegisterHotKey(hotkey, tx) => {
-                        let _ = tx.send(unregister_hotkey(
-                            &xdg,
-                            &mut hotkeys,
-                            hotkey,
-                        ));
+                    Some(ThreadMessage::UnRegisterHotKey(hotkey, tx)) => {
+                        let _ = tx.send(unregister_hotkey(&xdg, &mut hotkeys, hotkey).await);
                     }
-                    ThreadMessage::UnRegisterHotKeys(keys, tx) => {
-                        for hotkey in keys {
-                            if let Err(e) =
-                                unregister_hotkey(&xdg, &mut hotkeys, hotkey)
-                            {
-                                let _ = tx.send(Err(e));
-                            }
-                        }
-                        let _ = tx.send(Ok(()));
+                    Some(ThreadMessage::UnRegisterHotKeys(keys, tx)) => {
+                        let _ = tx.send(unregister_hotkeys(&xdg, &mut hotkeys, keys).await);
+                    }
+                    Some(ThreadMessage::AssignedTrigger(hotkey, tx)) => {
+                        let accelerator = keycode_to_freedesktop_spec(hotkey.key).map(|key| {
+                            format!("{}+{}", modifiers_to_freedesktop_spec(hotkey.mods), key)
+                        });
+                        let trigger = accelerator.and_then(|accelerator| xdg.assigned_trigger(&accelerator));
+                        let _ = tx.send(trigger);
                     }
-                    ThreadMessage::DropThread => {
-                        (drop(xdg));
+                    Some(ThreadMessage::DropThread) | None => {
+                        xdg.close().await;
                         return;
                     }
                 }
             }
-            xdg.activated();
-            xdg.deactivated();
-
-            std::thread::sleep(std::time::Duration::from_millis(50));
+            Some(activated_hotkey) = activated.next() => {
+                let id = activated_hotkey.shortcut_id().parse::<u32>().expect("Failed to parse shortcut id to u32: you should never see this error because id started as a u32.");
+                xdg.set_assigned_trigger(id, activated_hotkey.trigger_description().to_string());
+                GlobalHotKeyEvent::send(GlobalHotKeyEvent {
+                    id,
+                    state: crate::HotKeyState::Pressed,
+                });
+            }
+            Some(deactivated_hotkey) = deactivated.next() => {
+                let id = deactivated_hotkey.shortcut_id().parse::<u32>().expect("Failed to parse shortcut id to u32: you should never see this error because id started as a u32.");
+                GlobalHotKeyEvent::send(GlobalHotKeyEvent {
+                    id,
+                    state: crate::HotKeyState::Released,
+                });
+            }
         }
-    } else {
-        #[cfg(debug_assertions)]
-        eprintln!("Failed to open global shortcut portal, it might not be implemented on your desktop environment. The portal is required for global-hotkey crate under wayland try x11 instead.");
     }
 }
 
@@ -1,4 +1,7 @@
-use crate::GlobalHotKeyEvent;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
 use ashpd::{desktop::{
     global_shortcuts::{
         GlobalShortcuts,
@@ -10,29 +13,110 @@ use ashpd::{desktop::{
 }, Error, WindowIdentifier};
 use ashpd::zbus::export::futures_core::Stream;
 use rand::random;
-use tokio::runtime::Runtime;
-use tokio_stream::StreamExt;
 use crate::hotkey::HotKey;
-use crate::platform_impl::platform::wayland::runtime;
+
+const DEFAULT_DESCRIPTION: &str = "A hotkey created by the global hotkey rs library";
 
 pub(super) struct AsyncXdgs<'a> {
     global_shortcuts: GlobalShortcuts<'a>,
-    session: Session<'a, GlobalShortcuts<'a>>,
+    // The portal has no per-shortcut unbind call, so `unregister`/`unregister_all` replace this
+    // with a freshly bound session rather than mutating the existing one. The lock is held while
+    // `register` binds to the session and while a rebind swaps the new one in; it is not held
+    // while a rebind creates and binds the new session, so calls on one `AsyncXdgs` are not
+    // safe to run concurrently with a rebind.
+    session: tokio::sync::Mutex<Session<'a, GlobalShortcuts<'a>>>,
     window_identifier: WindowIdentifier,
+    // id -> accelerator for every shortcut this process knows is bound, persisted to disk so a
+    // restored session doesn't have to re-prompt the user through `bind_shortcuts`.
+    known: Mutex<HashMap<u32, String>>,
+    // id -> trigger the compositor actually assigned, which may differ from what we requested
+    // since the portal lets the user reassign a shortcut's key combination.
+    triggers: Mutex<HashMap<u32, String>>,
 }
 
 impl<'a> AsyncXdgs<'a> {
-    async fn new() -> Result<Self, ashpd::Error> {
+    pub(super) async fn new() -> Result<Self, ashpd::Error> {
+        let stored = SessionStore::load();
+
         let global_shortcuts = GlobalShortcuts::new().await?;
-        let session = global_shortcuts.create_session().await?;
+        let session = global_shortcuts
+            .create_session_with_restore_token(stored.restore_token.as_deref())
+            .await?;
+
+        // If we restored a prior session, ask the compositor what it already has bound instead
+        // of blindly trusting our own file (the user may have removed a shortcut in their
+        // compositor's settings UI since we last ran).
+        let mut known = HashMap::new();
+        if stored.restore_token.is_some() {
+            if let Ok(listed) = global_shortcuts.list_shortcuts(&session).await {
+                if let Ok(response) = listed.response() {
+                    for shortcut in response.shortcuts() {
+                        if let Ok(id) = shortcut.id().parse::<u32>() {
+                            if let Some(accelerator) = stored.shortcuts.get(&id) {
+                                known.insert(id, accelerator.clone());
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        let restore_token = session.restore_token().map(str::to_owned).or(stored.restore_token);
+        SessionStore { restore_token, shortcuts: known.clone() }.save();
+
         Ok(AsyncXdgs {
             global_shortcuts,
-            session,
+            session: tokio::sync::Mutex::new(session),
             window_identifier: WindowIdentifier::default(),
+            known: Mutex::new(known),
+            triggers: Mutex::new(HashMap::new()),
         })
     }
 
-    async fn register(&self, hotkey: String, hotkeys: &mut Vec<u32>) -> Result<(), ashpd::Error> {
+    /// ids of shortcuts recovered from a restored session, so the caller can seed its own
+    /// `hotkeys: Vec<u32>` bookkeeping without re-registering anything.
+    pub(super) fn known_hotkeys(&self) -> Vec<u32> {
+        self.known.lock().unwrap().keys().copied().collect()
+    }
+
+    /// The trigger the compositor actually assigned to the hotkey registered under this
+    /// accelerator, if we've seen it yet (either from the `bind_shortcuts` response or from an
+    /// `Activated` event). `triggers` is keyed by the portal's own shortcut id, which is a
+    /// separate value space from the caller's accelerator string, so resolve through `known`
+    /// first — the same accelerator -> id lookup `register` uses for dedup.
+    pub(super) fn assigned_trigger(&self, accelerator: &str) -> Option<String> {
+        let id = self
+            .known
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(_, bound)| bound.as_str() == accelerator)
+            .map(|(id, _)| *id)?;
+        self.triggers.lock().unwrap().get(&id).cloned()
+    }
+
+    pub(super) fn set_assigned_trigger(&self, id: u32, trigger: String) {
+        self.triggers.lock().unwrap().insert(id, trigger);
+    }
+
+    pub(super) async fn register(
+        &self,
+        hotkey: String,
+        description: Option<String>,
+        hotkeys: &mut Vec<u32>,
+    ) -> Result<(), ashpd::Error> {
+        if let Some(id) = self
+            .known
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(_, accelerator)| accelerator.as_str() == hotkey)
+            .map(|(id, _)| *id)
+        {
+            hotkeys.push(id);
+            return Ok(());
+        }
+
         let mut id;
         loop {
             id = random::<u32>();
@@ -42,111 +126,204 @@ impl<'a> AsyncXdgs<'a> {
                 continue
             }
         }
-        let shortcut = NewShortcut::new(id.clone().to_string(), "A hotkey created by the global hotkey rs library")
+        let shortcut = NewShortcut::new(id.clone().to_string(), description.as_deref().unwrap_or(DEFAULT_DESCRIPTION))
             .preferred_trigger(Some(hotkey.as_str()));
-        let shortcuts = self.global_shortcuts.bind_shortcuts(&self.session, &[shortcut], &self.window_identifier).await?.response()?.shortcuts().to_owned();
+        let session = self.session.lock().await;
+        let shortcuts = self.global_shortcuts.bind_shortcuts(&session, &[shortcut], &self.window_identifier).await?.response()?.shortcuts().to_owned();
+        drop(session);
         hotkeys.push(id);
 
+        if let Some(bound) = shortcuts.iter().find(|shortcut| shortcut.id().parse::<u32>() == Ok(id)) {
+            self.set_assigned_trigger(id, bound.trigger_description().to_string());
+        }
+
+        let mut known = self.known.lock().unwrap();
+        known.insert(id, hotkey);
+        let snapshot = known.clone();
+        drop(known);
+        self.persist(&snapshot).await;
+
         Ok(())
     }
 
-    async fn unregister(&self, hotkey: String, hotkeys: &mut Vec<u32>) -> Result<(), ashpd::Error> {
-        todo!()
+    pub(super) async fn unregister(&self, hotkey: String, hotkeys: &mut Vec<u32>) -> Result<(), ashpd::Error> {
+        let Some(id) = self
+            .known
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(_, accelerator)| accelerator.as_str() == hotkey)
+            .map(|(id, _)| *id)
+        else {
+            return Ok(());
+        };
+
+        let remaining: HashMap<u32, String> = self
+            .known
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(existing, _)| **existing != id)
+            .map(|(id, accelerator)| (*id, accelerator.clone()))
+            .collect();
+
+        // Only forget the shortcut once the rebind against the reduced set has actually
+        // succeeded, so a failure here leaves `known`/`hotkeys` in sync with what's really
+        // still bound instead of out ahead of it (a retry or a later `register` for the same
+        // accelerator would otherwise treat the still-bound shortcut as gone).
+        self.rebind_remaining(&remaining).await?;
+
+        self.known.lock().unwrap().remove(&id);
+        hotkeys.retain(|existing| *existing != id);
+
+        Ok(())
     }
 
-    async fn activated(&self) -> Result<(), Error> {
-        match self.global_shortcuts.receive_activated().await {
-            Ok(mut ok) => {
-                while let Some(activated_hotkey) = ok.next().await {
-                    let id = activated_hotkey.shortcut_id().parse::<u32>().expect("Failed to parse shortcut id to u32: you should never see this error because id started as a u32.");
-                    GlobalHotKeyEvent::send(GlobalHotKeyEvent {
-                        id,
-                        state: crate::HotKeyState::Pressed,
-                    });
-                    break;
-                }
-                Ok(())
+    pub(super) async fn unregister_all(&self, accelerators: &[String], hotkeys: &mut Vec<u32>) -> Result<(), ashpd::Error> {
+        let ids: Vec<u32> = {
+            let known = self.known.lock().unwrap();
+            accelerators
+                .iter()
+                .filter_map(|accelerator| {
+                    known
+                        .iter()
+                        .find(|(_, bound)| *bound == accelerator)
+                        .map(|(id, _)| *id)
+                })
+                .collect()
+        };
+
+        let remaining: HashMap<u32, String> = self
+            .known
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(existing, _)| !ids.contains(existing))
+            .map(|(id, accelerator)| (*id, accelerator.clone()))
+            .collect();
+
+        self.rebind_remaining(&remaining).await?;
+
+        {
+            let mut known = self.known.lock().unwrap();
+            for id in &ids {
+                known.remove(id);
             }
-            Err(err) => { Err(err) }
         }
+        hotkeys.retain(|existing| !ids.contains(existing));
+
+        Ok(())
     }
 
-    async fn deactivated(&self) -> Result<(), Error> {
-        match self.global_shortcuts.receive_deactivated().await {
-            Ok(mut ok) => {
-                while let Some(deactivated_hotkey) = ok.next().await {
-                    let id = deactivated_hotkey.shortcut_id().parse::<u32>().expect("Failed to parse shortcut id to u32: you should never see this error because id started as a u32.");
-                    GlobalHotKeyEvent::send(GlobalHotKeyEvent {
-                        id,
-                        state: crate::HotKeyState::Released,
-                    });
-                    break;
-                }
-                Ok(())
+    // There is no per-shortcut unbind in the `global_shortcuts` portal, so removal is done by
+    // rebinding `remaining` under a fresh session, preserving ids so in-flight activation events
+    // keep matching up with the caller's hotkeys. The old session is only retired once the new
+    // one is confirmed bound, so a failure here leaves the existing session (and the shortcuts
+    // still on it) intact instead of half-torn-down.
+    async fn rebind_remaining(&self, remaining: &HashMap<u32, String>) -> Result<(), ashpd::Error> {
+        let new_session = self.global_shortcuts.create_session_with_restore_token(None).await?;
+
+        if !remaining.is_empty() {
+            let shortcuts: Vec<NewShortcut> = remaining
+                .iter()
+                .map(|(id, accelerator)| {
+                    NewShortcut::new(id.to_string(), DEFAULT_DESCRIPTION)
+                        .preferred_trigger(Some(accelerator.as_str()))
+                })
+                .collect();
+            if let Err(err) = self
+                .global_shortcuts
+                .bind_shortcuts(&new_session, &shortcuts, &self.window_identifier)
+                .await
+            {
+                let _ = new_session.close().await;
+                return Err(err);
             }
-            Err(err) => { Err(err) }
         }
+
+        let mut session = self.session.lock().await;
+        let old_session = std::mem::replace(&mut *session, new_session);
+        drop(session);
+        let _ = old_session.close().await;
+
+        self.persist(&remaining).await;
+
+        Ok(())
     }
-    async fn drop(&self) {
-        let _ = self.session.close().await;
+
+    // Kept hot for the lifetime of the session: the caller `select!`s over these instead of
+    // opening a fresh stream and taking one item per poll, so no activation is dropped.
+    pub(super) async fn activated_stream(&self) -> Result<impl Stream<Item = Activated> + '_, Error> {
+        self.global_shortcuts.receive_activated().await
     }
-}
 
-pub(super) struct Xdgs<'a> {
-    inner: AsyncXdgs<'a>,
-    rt: Runtime,
-}
+    pub(super) async fn deactivated_stream(&self) -> Result<impl Stream<Item = Deactivated> + '_, Error> {
+        self.global_shortcuts.receive_deactivated().await
+    }
 
-impl<'a> Xdgs<'a> {
-    pub(super) fn new() -> ashpd::Result<Self> {
-        let rt = tokio::runtime::Builder::new_current_thread()
-            .enable_io()
-            .build()?;
-        match rt.block_on(runtime::AsyncXdgs::new()) {
-            Ok(inner) => {
-                Ok(
-                    Self {
-                        inner,
-                        rt,
-                    }
-                )
-            }
-            Err(err) => {
-                Err(ashpd::Error::NoResponse)
-            }
-        }
+    pub(super) async fn close(&self) {
+        let _ = self.session.lock().await.close().await;
     }
-    pub(super) fn register(&self, hotkey: String, hotkeys: &mut Vec<u32>) -> crate::Result<()> {
-        if let Err(err) = self.rt.block_on(self.inner.register(hotkey, hotkeys)) {
-            Err(crate::Error::FailedToRegister(err.to_string().into()))
-        } else {
-            Ok(())
+
+    async fn persist(&self, known: &HashMap<u32, String>) {
+        let restore_token = self.session.lock().await.restore_token().map(str::to_owned);
+        SessionStore {
+            restore_token,
+            shortcuts: known.clone(),
         }
+        .save();
     }
-    pub(super) fn unregister(&self, hotkey_str: String, hotkey: HotKey, hotkeys: &mut Vec<u32>) -> crate::Result<()> {
-        if self.rt.block_on(self.inner.unregister(hotkey_str, hotkeys)).is_err() {
-            Err(crate::Error::FailedToUnRegister(hotkey))
-        } else {
-            Ok(())
-        }
 }
-    pub(super) fn activated(&self) {
-        self.rt.block_on(self.inner.activated());
-    }
 
-    pub(super) fn deactivated(&self) {
-        self.rt.block_on(self.inner.deactivated());
-    }
+struct SessionStore {
+    restore_token: Option<String>,
+    shortcuts: HashMap<u32, String>,
 }
 
-impl<'a> Drop for Xdgs<'a> {
-    fn drop(&mut self) {
-        self.rt.block_on(self.inner.drop());
-    }
-}
+impl SessionStore {
+    fn load() -> Self {
+        let mut store = SessionStore { restore_token: None, shortcuts: HashMap::new() };
 
+        let Some(path) = Self::path() else { return store };
+        let Ok(contents) = std::fs::read_to_string(path) else { return store };
+
+        let mut lines = contents.lines();
+        if let Some(token) = lines.next() {
+            if !token.is_empty() {
+                store.restore_token = Some(token.to_string());
+            }
+        }
+        for line in lines {
+            if let Some((id, accelerator)) = line.split_once('=') {
+                if let Ok(id) = id.parse::<u32>() {
+                    store.shortcuts.insert(id, accelerator.to_string());
+                }
+            }
+        }
 
+        store
+    }
 
+    fn save(&self) {
+        let Some(path) = Self::path() else { return };
+        if let Some(parent) = path.parent() {
+            let _ = std::fs::create_dir_all(parent);
+        }
 
+        let mut contents = String::new();
+        contents.push_str(self.restore_token.as_deref().unwrap_or(""));
+        contents.push('\n');
+        for (id, accelerator) in &self.shortcuts {
+            contents.push_str(&format!("{}={}\n", id, accelerator));
+        }
 
+        let _ = std::fs::write(path, contents);
+    }
 
+    fn path() -> Option<PathBuf> {
+        let config_home = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+        Some(config_home.join("global-hotkey").join("wayland-session"))
+    }
+}